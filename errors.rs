@@ -12,13 +12,25 @@ pub enum AppError {
     #[error("Username taken")]
     UsernameTaken,
 
+    #[error("Invalid username")]
+    InvalidUsername,
+
     #[error("Invalid credentials")]
     InvalidCredentials,
 
     #[error("Message not found")]
     MessageNotFound,
 
+    #[error("Proof-of-work score below the required threshold")]
+    InsufficientProofOfWork,
+
+    #[error("Proof-of-work nonce already used")]
+    ReplayedProofOfWork,
+
     #[error("Internal Error: {0}")]
     Internal(String),
+
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
@@ -1,86 +1,120 @@
 // loveletter/src/padlock.rs
 
-use ring::rand::{SecureRandom, SystemRandom};
-use ring::aead::{LessSafeKey, UnboundKey, AES_256_GCM, Aad, Nonce, NONCE_LEN};
+use hkdf::Hkdf;
+use rand_core::OsRng;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
 use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+/// A message encrypted to a single recipient's X25519 public key.
+///
+/// `ephemeral_public` travels alongside the ciphertext so the recipient
+/// can redo the ECDH with their own private key and recover the same
+/// shared secret the sender used.
+pub struct SealedMessage {
+    pub ephemeral_public: [u8; 32],
+    pub ciphertext: Vec<u8>, // nonce || AES-256-GCM ciphertext+tag
+    pub mac: Vec<u8>,
+}
 
-// This struct now holds two keys:
-// 1) The AES-GCM key for encryption
-// 2) The HMAC key for hashing
-pub struct Padlock {
-    encryption_key: LessSafeKey,
-    hmac_key: hmac::Key, // key for HMAC
+// Per-message AES-256-GCM + HMAC-SHA256 keys derived from an X25519 shared
+// secret. Never stored; recomputed on both ends for every message.
+struct DerivedKeys {
+    enc_key: [u8; 32],
+    mac_key: [u8; 32],
 }
 
-impl Padlock {
-    /// Create a new Padlock with random 256-bit keys
-    pub fn new() -> Self {
-        let sys_rng = SystemRandom::new();
+/// Stateless ECIES box: every message is encrypted to its recipient's
+/// X25519 public key with a fresh ephemeral keypair, rather than a single
+/// server-wide key the server itself could use to read everything.
+pub struct Padlock;
 
-        // --- Encryption Key (AES-GCM) ---
-        let mut enc_bytes = [0u8; 32];
-        sys_rng.fill(&mut enc_bytes).unwrap();
-        let unbound_key = UnboundKey::new(&AES_256_GCM, &enc_bytes).unwrap();
-        let encryption_key = LessSafeKey::new(unbound_key);
+impl Padlock {
+    fn derive_keys(shared_secret: &[u8; 32]) -> DerivedKeys {
+        let hk = Hkdf::<Sha256>::new(None, shared_secret);
+        let mut okm = [0u8; 64];
+        hk.expand(b"loveletter-ecies-v1", &mut okm)
+            .expect("64 is a valid HKDF-SHA256 output length");
+        let mut enc_key = [0u8; 32];
+        let mut mac_key = [0u8; 32];
+        enc_key.copy_from_slice(&okm[..32]);
+        mac_key.copy_from_slice(&okm[32..]);
+        DerivedKeys { enc_key, mac_key }
+    }
 
-        // --- HMAC Key (SHA256) ---
-        let mut hmac_bytes = [0u8; 32];
-        sys_rng.fill(&mut hmac_bytes).unwrap();
-        let hmac_key = hmac::Key::new(hmac::HMAC_SHA256, &hmac_bytes);
+    /// Encrypt `plaintext` to `recipient_public`: generate an ephemeral
+    /// X25519 keypair, derive AES-256-GCM + HMAC keys via HKDF-SHA256 from
+    /// the ECDH shared secret, then seal the body and MAC it.
+    pub fn seal(recipient_public: &[u8; 32], plaintext: &[u8]) -> SealedMessage {
+        let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral_secret);
+        let shared = ephemeral_secret.diffie_hellman(&PublicKey::from(*recipient_public));
+        let keys = Self::derive_keys(shared.as_bytes());
 
-        Padlock {
-            encryption_key,
-            hmac_key,
-        }
-    }
+        let unbound_key = UnboundKey::new(&AES_256_GCM, &keys.enc_key).expect("32-byte key");
+        let aes_key = LessSafeKey::new(unbound_key);
 
-    /// Encrypt data with AES-256-GCM (same as before)
-    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
         let sys_rng = SystemRandom::new();
         let mut nonce_bytes = [0u8; NONCE_LEN];
         sys_rng.fill(&mut nonce_bytes).unwrap();
-
         let nonce = Nonce::assume_unique_for_key(nonce_bytes);
-        let mut in_out = plaintext.to_vec();
-        in_out.resize(in_out.len() + 16, 0); // 16 bytes for GCM tag
 
-        self.encryption_key
+        let mut in_out = plaintext.to_vec();
+        aes_key
             .seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out)
-            .expect("Encryption failed");
+            .expect("encryption failed");
 
-        // Combine nonce + ciphertext
         let mut ciphertext = nonce_bytes.to_vec();
         ciphertext.extend_from_slice(&in_out);
-        ciphertext
+
+        let hmac_key = hmac::Key::new(hmac::HMAC_SHA256, &keys.mac_key);
+        let mac = hmac::sign(&hmac_key, plaintext).as_ref().to_vec();
+
+        SealedMessage {
+            ephemeral_public: *ephemeral_public.as_bytes(),
+            ciphertext,
+            mac,
+        }
     }
 
-    /// Decrypt data with AES-256-GCM
-    pub fn decrypt(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+    /// Recover the plaintext for a message sealed with [`Padlock::seal`],
+    /// given the recipient's static X25519 secret.
+    ///
+    /// Returns `(plaintext, mac_valid)` rather than failing outright on a
+    /// bad MAC, so a caller can surface tampering as a warning instead of
+    /// silently dropping the message (mirrors the old shared-key path).
+    /// Only the recipient holds the private key this needs, so the server
+    /// in this crate never calls it — it's here for whatever client
+    /// eventually consumes `FetchMessages` responses.
+    pub fn open(
+        recipient_secret: &StaticSecret,
+        ephemeral_public: &[u8; 32],
+        ciphertext: &[u8],
+        mac: &[u8],
+    ) -> Option<(Vec<u8>, bool)> {
         if ciphertext.len() < NONCE_LEN + 16 {
             return None;
         }
 
+        let shared = recipient_secret.diffie_hellman(&PublicKey::from(*ephemeral_public));
+        let keys = Self::derive_keys(shared.as_bytes());
+
         let (nonce_bytes, encrypted) = ciphertext.split_at(NONCE_LEN);
         let nonce = Nonce::try_assume_unique_for_key(nonce_bytes).ok()?;
 
+        let unbound_key = UnboundKey::new(&AES_256_GCM, &keys.enc_key).ok()?;
+        let aes_key = LessSafeKey::new(unbound_key);
         let mut in_out = encrypted.to_vec();
-        let res = self
-            .encryption_key
+        let plaintext = aes_key
             .open_in_place(nonce, Aad::empty(), &mut in_out)
-            .ok()?;
+            .ok()?
+            .to_vec();
 
-        Some(res.to_vec())
-    }
+        let hmac_key = hmac::Key::new(hmac::HMAC_SHA256, &keys.mac_key);
+        let mac_valid = hmac::verify(&hmac_key, &plaintext, mac).is_ok();
 
-    /// Compute HMAC-SHA256 of some plaintext
-    pub fn compute_hmac(&self, data: &[u8]) -> Vec<u8> {
-        let tag = hmac::sign(&self.hmac_key, data);
-        tag.as_ref().to_vec()
-    }
-
-    /// Verify HMAC-SHA256 (returns `true` if valid, `false` if mismatch)
-    pub fn verify_hmac(&self, data: &[u8], expected_tag: &[u8]) -> bool {
-        hmac::verify(&self.hmac_key, data, expected_tag).is_ok()
+        Some((plaintext, mac_valid))
     }
 }
-
@@ -0,0 +1,125 @@
+// src/antispam.rs
+//
+// Proof-of-work spam throttling and size-bounded message pruning.
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::errors::{AppError, AppResult};
+use crate::inputs::UserMessage;
+
+/// Minimum proof-of-work score required for `send_message` to accept an
+/// envelope. Tuned so a small, short-lived message needs a modest nonce
+/// search, while large or long-lived ones need disproportionately more.
+pub const MIN_POW: f64 = 1.0;
+
+/// Stored ciphertext bytes, per recipient, above which pruning kicks in.
+/// Applied per recipient (see `clientinp::prune_stored`) since messages are
+/// now stored keyed by recipient rather than in one global list.
+pub const TARGET_STORE_BYTES: usize = 16 * 1024 * 1024; // 16 MiB
+
+/// The longest a sender is allowed to ask a message to live for. Without a
+/// cap, `ttl_seconds` flows straight into `now + ttl_seconds` when computing
+/// `expires_at`, and an attacker-chosen `u64::MAX` would overflow that add.
+pub const MAX_TTL_SECONDS: u64 = 30 * 24 * 60 * 60; // 30 days
+
+/// Clamp a sender-supplied TTL into `1..=MAX_TTL_SECONDS`.
+pub fn clamp_ttl(ttl_seconds: u64) -> u64 {
+    ttl_seconds.clamp(1, MAX_TTL_SECONDS)
+}
+
+/// The fields that go into the proof-of-work hash: everything that
+/// identifies the message except the sender-chosen nonce itself.
+#[derive(Serialize)]
+struct PowEnvelope<'a> {
+    from: &'a str,
+    to: &'a str,
+    body: &'a str,
+    ttl_seconds: u64,
+    topics: &'a [[u8; 4]],
+}
+
+fn leading_zero_bits(digest: &[u8; 32]) -> u32 {
+    let mut zeros = 0;
+    for byte in digest {
+        if *byte == 0 {
+            zeros += 8;
+            continue;
+        }
+        zeros += byte.leading_zeros();
+        break;
+    }
+    zeros
+}
+
+/// Compute `pow = 2^z / (envelope_size_bytes * ttl_seconds)`, where `z` is
+/// the number of leading zero bits of `SHA256(serialized_envelope || nonce)`,
+/// along with that digest itself so the caller can guard against the same
+/// `(envelope, nonce)` being replayed to mint unlimited messages out of one
+/// passing nonce search.
+pub fn compute_pow(
+    from: &str,
+    to: &str,
+    body: &str,
+    nonce: u64,
+    ttl_seconds: u64,
+    topics: &[[u8; 4]],
+) -> AppResult<(f64, [u8; 32])> {
+    let envelope = PowEnvelope { from, to, body, ttl_seconds, topics };
+    let serialized = serde_json::to_vec(&envelope)
+        .map_err(|e| AppError::Internal(format!("failed to serialize envelope: {e}")))?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(&serialized);
+    hasher.update(nonce.to_be_bytes());
+    let digest: [u8; 32] = hasher.finalize().into();
+
+    let z = leading_zero_bits(&digest);
+    let denom = (serialized.len() as f64) * (ttl_seconds.max(1) as f64);
+    Ok((2f64.powi(z as i32) / denom, digest))
+}
+
+/// Reject messages whose PoW score falls below [`MIN_POW`].
+pub fn check_pow(pow: f64) -> AppResult<()> {
+    if pow < MIN_POW {
+        return Err(AppError::InsufficientProofOfWork);
+    }
+    Ok(())
+}
+
+/// Reject a PoW digest that's already been spent, and otherwise record it
+/// until its message would have expired anyway. Without this, recomputing
+/// `compute_pow` over the same sender-supplied fields always reproduces the
+/// same passing score, so one nonce search would otherwise buy unlimited
+/// resends of the identical envelope.
+pub fn check_replay(seen: &mut HashMap<[u8; 32], u64>, digest: [u8; 32], now: u64, ttl_seconds: u64) -> AppResult<()> {
+    seen.retain(|_, expires_at| *expires_at > now);
+    if seen.contains_key(&digest) {
+        return Err(AppError::ReplayedProofOfWork);
+    }
+    seen.insert(digest, now.saturating_add(ttl_seconds));
+    Ok(())
+}
+
+fn stored_size(m: &UserMessage) -> usize {
+    m.body_enc.len() + m.body_hash.len() + m.ephemeral_pubkey.len()
+}
+
+/// Drop expired messages, then evict the lowest-PoW messages until the
+/// store is back under [`TARGET_STORE_BYTES`].
+pub fn prune(messages: &mut Vec<UserMessage>, now: u64) {
+    messages.retain(|m| m.expires_at > now);
+
+    let mut total: usize = messages.iter().map(stored_size).sum();
+    if total <= TARGET_STORE_BYTES {
+        return;
+    }
+
+    messages.sort_by(|a, b| a.pow.partial_cmp(&b.pow).unwrap_or(std::cmp::Ordering::Equal));
+    while total > TARGET_STORE_BYTES && !messages.is_empty() {
+        let evicted = messages.remove(0);
+        total -= stored_size(&evicted);
+    }
+}
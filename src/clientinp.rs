@@ -2,158 +2,336 @@
 
 use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use ed25519_dalek::SigningKey;
+use rand_core::OsRng;
+use serde::Serialize;
 use uuid::Uuid;
 
+use crate::antispam::{check_pow, check_replay, clamp_ttl, compute_pow, prune};
 use crate::padlock::Padlock;
 use crate::errors::{AppError, AppResult};
-use crate::inputs::{ServerCommand, UserMessage};
+use crate::inputs::{Filter, ServerCommand, UserMessage};
+use crate::password::{hash_password, verify_password};
+use crate::store::{Store, UserRecord};
 
-#[derive(Clone)]
-pub struct ServerState {
-    inner: Arc<Mutex<ServerInner>>,
+fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system clock is before the unix epoch")
+        .as_secs()
 }
 
-struct ServerInner {
-    // A single Padlock with both encryption_key + hmac_key
-    padlock: Padlock,
+pub struct ServerState<S: Store> {
+    inner: Arc<Mutex<ServerInner>>,
 
-    // username -> password
-    users: HashMap<String, String>,
+    // The server's long-lived Ed25519 identity, presented during the
+    // session handshake. Not behind the mutex: it never changes.
+    pub identity: Arc<SigningKey>,
 
-    // who is "logged in"
-    logged_in_users: HashSet<String>,
+    // Durable user/message storage. Swappable: `store::MemoryStore` for
+    // the original in-process behavior, `store::ObjectStoreBackend` to
+    // survive restarts and scale beyond one process.
+    pub store: Arc<S>,
+}
 
-    // store messages
-    messages: Vec<UserMessage>,
+impl<S: Store> Clone for ServerState<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            identity: self.identity.clone(),
+            store: self.store.clone(),
+        }
+    }
 }
 
-impl ServerState {
-    pub fn new() -> Self {
+#[derive(Default)]
+struct ServerInner {
+    // username -> active topic filters, for pub/sub delivery on top of
+    // direct `to` addressing -- in-process only, not persisted to the store
+    subscriptions: HashMap<String, Vec<Filter>>,
+
+    // PoW digests already spent by `send_message`, so a winning nonce can't
+    // be resent to mint unlimited copies of the same envelope -- entries
+    // are pruned once their originating message would have expired anyway.
+    seen_pow_digests: HashMap<[u8; 32], u64>,
+}
+
+impl<S: Store> ServerState<S> {
+    pub fn new(store: S) -> Self {
         Self {
-            inner: Arc::new(Mutex::new(ServerInner {
-                padlock: Padlock::new(),
-                users: HashMap::new(),
-                logged_in_users: HashSet::new(),
-                messages: Vec::new(),
-            })),
+            inner: Arc::new(Mutex::new(ServerInner::default())),
+            identity: Arc::new(SigningKey::generate(&mut OsRng)),
+            store: Arc::new(store),
         }
     }
 }
 
+/// The username/`from` a command acts as. The session layer uses this to
+/// bind a connection to the user it signed in as and reject commands
+/// issued for a different principal without re-checking credentials.
+pub fn command_principal(cmd: &ServerCommand) -> &str {
+    match cmd {
+        ServerCommand::SignUp { username, .. } => username,
+        ServerCommand::SignIn { username, .. } => username,
+        ServerCommand::SignOut { username } => username,
+        ServerCommand::SendMessage { from, .. } => from,
+        ServerCommand::FetchMessages { username } => username,
+        ServerCommand::DeleteMessage { username, .. } => username,
+        ServerCommand::Subscribe { username, .. } => username,
+        ServerCommand::Unsubscribe { username, .. } => username,
+    }
+}
+
 /// Process the incoming client command
-pub async fn process_client(cmd: ServerCommand, state: ServerState) -> AppResult<Option<String>> {
+pub async fn process_client<S: Store>(cmd: ServerCommand, state: ServerState<S>) -> AppResult<Option<String>> {
     match cmd {
-        ServerCommand::SignUp { username, password } => sign_up(username, password, state),
-        ServerCommand::SignIn { username, password } => sign_in(username, password, state),
+        ServerCommand::SignUp { username, password, public_key } => {
+            sign_up(username, password, public_key, state).await
+        }
+        ServerCommand::SignIn { username, password } => sign_in(username, password, state).await,
         ServerCommand::SignOut { username } => sign_out(username, state),
-        ServerCommand::SendMessage { from, to, body } => send_message(from, to, body, state),
-        ServerCommand::FetchMessages { username } => fetch_messages(username, state),
-        ServerCommand::DeleteMessage { username, msg_id } => delete_message(username, msg_id, state),
+        ServerCommand::SendMessage { from, to, body, nonce, ttl_seconds, topics } => {
+            send_message(from, to, body, nonce, ttl_seconds, topics, state).await
+        }
+        ServerCommand::FetchMessages { username } => fetch_messages(username, state).await,
+        ServerCommand::DeleteMessage { username, msg_id } => delete_message(username, msg_id, state).await,
+        ServerCommand::Subscribe { username, filter } => subscribe(username, filter, state),
+        ServerCommand::Unsubscribe { username, filter } => unsubscribe(username, filter, state),
+    }
+}
+
+/// Usernames become path segments in storage backends (see
+/// `store::ObjectStoreBackend`), so they're restricted to a small
+/// allowlisted charset up front rather than trusted as opaque strings.
+const MAX_USERNAME_LEN: usize = 32;
+
+fn validate_username(username: &str) -> AppResult<()> {
+    let valid = !username.is_empty()
+        && username.len() <= MAX_USERNAME_LEN
+        && username.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidUsername)
     }
 }
 
 /// Create a new user
-fn sign_up(username: String, password: String, state: ServerState) -> AppResult<Option<String>> {
-    let mut guard = state.inner.lock().unwrap();
-    if guard.users.contains_key(&username) {
+async fn sign_up<S: Store>(
+    username: String,
+    password: String,
+    public_key: Vec<u8>,
+    state: ServerState<S>,
+) -> AppResult<Option<String>> {
+    validate_username(&username)?;
+    let public_key: [u8; 32] = public_key
+        .try_into()
+        .map_err(|_| AppError::Internal("public_key must be 32 bytes".to_string()))?;
+
+    if state.store.get_user(&username).await?.is_some() {
         return Err(AppError::UsernameTaken);
     }
-    guard.users.insert(username, password);
+    let hashed = hash_password(&password)?;
+    state
+        .store
+        .put_user(&username, UserRecord { password_hash: hashed, public_key })
+        .await?;
     Ok(Some("User created".to_string()))
 }
 
+/// A hash of a fixed, never-used dummy password, computed once and reused
+/// as the comparison target when `sign_in` can't find `username`. That way
+/// the not-found path still pays a real Argon2id hash, instead of
+/// returning early and leaking which usernames are registered through
+/// response timing.
+fn dummy_password_hash() -> &'static str {
+    static DUMMY: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+    DUMMY.get_or_init(|| {
+        hash_password("not-a-real-account-constant-time-padding")
+            .expect("hashing a fixed dummy password never fails")
+    })
+}
+
 /// Sign in (validate password)
-fn sign_in(username: String, password: String, state: ServerState) -> AppResult<Option<String>> {
-    let mut guard = state.inner.lock().unwrap();
-    let stored_pass = guard.users.get(&username).ok_or(AppError::UserNotFound)?;
-    if *stored_pass != password {
+///
+/// Deliberately returns the same `InvalidCredentials` error whether the
+/// username doesn't exist or the password is wrong, and does the same
+/// amount of work either way, so a caller can't use the response -- or its
+/// timing -- to enumerate registered usernames.
+async fn sign_in<S: Store>(username: String, password: String, state: ServerState<S>) -> AppResult<Option<String>> {
+    let record = state.store.get_user(&username).await?;
+    let stored_hash = record.as_ref().map_or_else(|| dummy_password_hash(), |r| r.password_hash.as_str());
+    let password_ok = verify_password(stored_hash, &password);
+
+    if record.is_none() || !password_ok {
         return Err(AppError::InvalidCredentials);
     }
-    guard.logged_in_users.insert(username.clone());
     Ok(Some(format!("{} signed in", username)))
 }
 
 /// Sign out
-fn sign_out(username: String, state: ServerState) -> AppResult<Option<String>> {
-    let mut guard = state.inner.lock().unwrap();
-    guard.logged_in_users.remove(&username);
+///
+/// The session's `bound_username` (see `main::handle_client`) is the sole
+/// record of who's signed in, so there's nothing left to update here.
+fn sign_out<S: Store>(username: String, _state: ServerState<S>) -> AppResult<Option<String>> {
     Ok(Some(format!("{} signed out", username)))
 }
 
-/// Send a message: now we also compute a HMAC of the *plaintext*
-fn send_message(from: String, to: String, body: String, state: ServerState) -> AppResult<Option<String>> {
-    let mut guard = state.inner.lock().unwrap();
-    // Ensure "from" user is logged in
-    if !guard.logged_in_users.contains(&from) {
-        return Err(AppError::InvalidCredentials);
-    }
-
-    // 1. Encrypt
-    let ciphertext = guard.padlock.encrypt(body.as_bytes());
+/// Send a message: ECIES-seal the body to the recipient's X25519 public
+/// key, so the server stores only ciphertext it cannot itself decrypt.
+///
+/// Requires the sender to supply a proof-of-work `nonce`: the message is
+/// rejected if its PoW score falls below the configured threshold, which
+/// pushes the cost of flooding the store onto the sender.
+///
+/// The caller is trusted to already be the session's bound, signed-in
+/// user (see `command_principal` and the handshake in `session`), so
+/// there's no separate `logged_in_users` check here.
+///
+/// Topic subscribers aren't a live query at fetch time anymore now that
+/// storage is keyed by recipient: a message that matches a subscriber's
+/// filter is additionally stored under that subscriber, right here at
+/// send time.
+async fn send_message<S: Store>(
+    from: String,
+    to: String,
+    body: String,
+    nonce: u64,
+    ttl_seconds: u64,
+    topics: Vec<[u8; 4]>,
+    state: ServerState<S>,
+) -> AppResult<Option<String>> {
+    let ttl_seconds = clamp_ttl(ttl_seconds);
+    let (pow, digest) = compute_pow(&from, &to, &body, nonce, ttl_seconds, &topics)?;
+    check_pow(pow)?;
+    let now = now_unix();
+    check_replay(&mut state.inner.lock().unwrap().seen_pow_digests, digest, now, ttl_seconds)?;
 
-    // 2. Compute an HMAC of the *plaintext*, not the ciphertext
-    //    (You could do it either way, but hashing plaintext is more common if you just want
-    //     to confirm the original message’s integrity.)
-    let digest = guard.padlock.compute_hmac(body.as_bytes());
+    let recipient = state.store.get_user(&to).await?.ok_or(AppError::UserNotFound)?;
+    let sealed = Padlock::seal(&recipient.public_key, body.as_bytes());
 
     let msg = UserMessage {
         id: Uuid::new_v4().to_string(),
         from,
-        to,
-        body_enc: ciphertext,
-        body_hash: digest, // store the HMAC
+        to: to.clone(),
+        body_enc: sealed.ciphertext,
+        body_hash: sealed.mac,
+        ephemeral_pubkey: sealed.ephemeral_public.to_vec(),
+        pow,
+        ttl_seconds,
+        expires_at: now.saturating_add(ttl_seconds),
+        topics,
     };
 
-    guard.messages.push(msg);
+    state.store.put_message(msg.clone()).await?;
+    prune_stored(&state, &to, now).await?;
+
+    let subscribers: Vec<String> = {
+        let guard = state.inner.lock().unwrap();
+        guard
+            .subscriptions
+            .iter()
+            .filter(|(user, filters)| user.as_str() != to && filters.iter().any(|f| f.matches(&msg)))
+            .map(|(user, _)| user.clone())
+            .collect()
+    };
+    for subscriber in subscribers {
+        // The direct recipient's copy was sealed to *their* X25519 key, so
+        // it's unreadable to anyone else; each subscriber needs their own
+        // seal of the same plaintext against their own public key.
+        let Some(subscriber_user) = state.store.get_user(&subscriber).await? else {
+            continue;
+        };
+        let sealed_for_subscriber = Padlock::seal(&subscriber_user.public_key, body.as_bytes());
+        let fanned_out = UserMessage {
+            id: msg.id.clone(),
+            from: msg.from.clone(),
+            to: subscriber.clone(),
+            body_enc: sealed_for_subscriber.ciphertext,
+            body_hash: sealed_for_subscriber.mac,
+            ephemeral_pubkey: sealed_for_subscriber.ephemeral_public.to_vec(),
+            pow: msg.pow,
+            ttl_seconds: msg.ttl_seconds,
+            expires_at: msg.expires_at,
+            topics: msg.topics.clone(),
+        };
+        state.store.put_message(fanned_out).await?;
+        prune_stored(&state, &subscriber, now).await?;
+    }
 
     Ok(Some("Message sent".to_string()))
 }
 
-/// Fetch messages for a user: decrypt + verify HMAC
-fn fetch_messages(username: String, state: ServerState) -> AppResult<Option<String>> {
-    let guard = state.inner.lock().unwrap();
-    if !guard.logged_in_users.contains(&username) {
-        return Err(AppError::InvalidCredentials);
-    }
+/// Drop `username`'s expired messages, then evict their lowest-PoW
+/// messages until they're back under the store's size target.
+async fn prune_stored<S: Store>(state: &ServerState<S>, username: &str, now: u64) -> AppResult<()> {
+    let mut messages = state.store.messages_for(username).await?;
+    let original_ids: HashSet<String> = messages.iter().map(|m| m.id.clone()).collect();
 
-    // Gather all messages for "username"
-    let mut results = vec![];
-    for m in guard.messages.iter().filter(|msg| msg.to == username) {
-        // 1. Decrypt the ciphertext
-        if let Some(decrypted_bytes) = guard.padlock.decrypt(&m.body_enc) {
-            // 2. Verify HMAC
-            let valid_hash = guard.padlock.verify_hmac(&decrypted_bytes, &m.body_hash);
-            let body_str = String::from_utf8_lossy(&decrypted_bytes).to_string();
-
-            let mut result_str = format!("MsgID: {}, From: {}, Body: {}", m.id, m.from, body_str);
-            if !valid_hash {
-                // The HMAC doesn’t match what we stored – possible tampering!
-                result_str.push_str(" [WARNING: HMAC verification failed!]");
-            }
-            results.push(result_str);
-        } else {
-            // Could not decrypt (should never happen if everything is consistent)
-            results.push(format!("MsgID: {}, [ERROR decrypting message]", m.id));
-        }
+    prune(&mut messages, now);
+    let kept_ids: HashSet<String> = messages.iter().map(|m| m.id.clone()).collect();
+
+    for evicted_id in original_ids.difference(&kept_ids) {
+        state.store.delete_message(username, evicted_id).await?;
     }
+    Ok(())
+}
 
-    // Return JSON array of messages
-    Ok(Some(serde_json::to_string(&results).unwrap()))
+/// Subscribe to messages matching `filter`, in addition to whatever is
+/// directly addressed to `username`.
+fn subscribe<S: Store>(username: String, filter: Filter, state: ServerState<S>) -> AppResult<Option<String>> {
+    let mut guard = state.inner.lock().unwrap();
+    guard.subscriptions.entry(username).or_default().push(filter);
+    Ok(Some("Subscribed".to_string()))
 }
 
-/// Delete a message
-fn delete_message(username: String, msg_id: String, state: ServerState) -> AppResult<Option<String>> {
+/// Remove a previously registered filter
+fn unsubscribe<S: Store>(username: String, filter: Filter, state: ServerState<S>) -> AppResult<Option<String>> {
     let mut guard = state.inner.lock().unwrap();
-    if !guard.logged_in_users.contains(&username) {
-        return Err(AppError::InvalidCredentials);
+    if let Some(filters) = guard.subscriptions.get_mut(&username) {
+        filters.retain(|f| f != &filter);
     }
+    Ok(Some("Unsubscribed".to_string()))
+}
 
-    let len_before = guard.messages.len();
-    guard.messages.retain(|m| !(m.to == username && m.id == msg_id));
-    let len_after = guard.messages.len();
+/// A message as handed back to its recipient: still sealed, since only
+/// the recipient's private key (held client-side) can open it.
+#[derive(Serialize)]
+struct EncryptedEnvelope {
+    id: String,
+    from: String,
+    body_enc: Vec<u8>,
+    body_hash: Vec<u8>,
+    ephemeral_pubkey: Vec<u8>,
+}
+
+/// Fetch messages for a user: the server hands back sealed envelopes as-is,
+/// it has no key that can decrypt them. Includes direct-addressed messages
+/// plus anything matching the user's active topic filters, since those are
+/// fanned out into the user's own store entries at send time.
+async fn fetch_messages<S: Store>(username: String, state: ServerState<S>) -> AppResult<Option<String>> {
+    let messages = state.store.messages_for(&username).await?;
+
+    let envelopes: Vec<EncryptedEnvelope> = messages
+        .iter()
+        .map(|m| EncryptedEnvelope {
+            id: m.id.clone(),
+            from: m.from.clone(),
+            body_enc: m.body_enc.clone(),
+            body_hash: m.body_hash.clone(),
+            ephemeral_pubkey: m.ephemeral_pubkey.clone(),
+        })
+        .collect();
 
-    if len_before == len_after {
+    // Return JSON array of sealed envelopes for the client to open with Padlock::open
+    Ok(Some(serde_json::to_string(&envelopes).unwrap()))
+}
+
+/// Delete a message
+async fn delete_message<S: Store>(username: String, msg_id: String, state: ServerState<S>) -> AppResult<Option<String>> {
+    if !state.store.delete_message(&username, &msg_id).await? {
         return Err(AppError::MessageNotFound);
     }
     Ok(Some(format!("Deleted message {}", msg_id)))
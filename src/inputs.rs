@@ -5,12 +5,45 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "action")]
 pub enum ServerCommand {
-    SignUp { username: String, password: String },
+    // `public_key` is the client's X25519 public key (32 bytes), generated
+    // and kept private client-side; the server only ever sees the public half.
+    SignUp { username: String, password: String, public_key: Vec<u8> },
     SignIn { username: String, password: String },
     SignOut { username: String },
-    SendMessage { from: String, to: String, body: String },
+    // `nonce` is the sender's proof-of-work nonce; `ttl_seconds` is how
+    // long the message is allowed to sit in the store before it expires.
+    // `topics` additionally tags the message for subscribers whose filter
+    // matches, on top of the direct `to` delivery.
+    SendMessage {
+        from: String,
+        to: String,
+        body: String,
+        nonce: u64,
+        ttl_seconds: u64,
+        #[serde(default)]
+        topics: Vec<[u8; 4]>,
+    },
     FetchMessages { username: String },
     DeleteMessage { username: String, msg_id: String },
+    Subscribe { username: String, filter: Filter },
+    Unsubscribe { username: String, filter: Filter },
+}
+
+/// Matches messages tagged with any of `topics`, optionally narrowed to a
+/// single sender via `from`. Used for group channels and interest
+/// subscriptions, layered on top of the single-recipient `to` model.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Filter {
+    pub topics: Vec<[u8; 4]>,
+    pub from: Option<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, msg: &UserMessage) -> bool {
+        let topic_match = self.topics.iter().any(|t| msg.topics.contains(t));
+        let from_match = self.from.as_deref().map_or(true, |from| from == msg.from);
+        topic_match && from_match
+    }
 }
 
 /// Shape of a user message in memory
@@ -19,7 +52,12 @@ pub struct UserMessage {
     pub id: String,
     pub from: String,
     pub to: String,
-    pub body_enc: Vec<u8>,  // Encrypted message bytes
-    pub body_hash: Vec<u8>, // **NEW**: additional digest of plaintext
+    pub body_enc: Vec<u8>,        // AES-256-GCM ciphertext, ECIES-sealed to `to`'s public key
+    pub body_hash: Vec<u8>,       // HMAC-SHA256 over the plaintext, keyed from the same ECIES exchange
+    pub ephemeral_pubkey: Vec<u8>, // sender's ephemeral X25519 public key for this message
+    pub pow: f64,                  // proof-of-work score computed at send time
+    pub ttl_seconds: u64,
+    pub expires_at: u64,           // unix timestamp; message is pruned once past this
+    pub topics: Vec<[u8; 4]>,      // topic tags for subscription-based routing
 }
 
@@ -1,16 +1,24 @@
 // src/main.rs
 
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
 mod padlock;
 mod errors;
 mod inputs;
+mod password;
+mod antispam;
+mod session;
+mod store;
 mod clientinp;
 
-use crate::clientinp::process_client;
-use crate::errors::AppResult;
-use crate::inputs::{ServerCommand, UserMessage};
+use crate::clientinp::{command_principal, process_client};
+use crate::errors::{AppError, AppResult};
+use crate::inputs::ServerCommand;
+use crate::session::HandshakeHello;
+use crate::store::{MemoryStore, Store};
 
 #[tokio::main]
 async fn main() -> AppResult<()> {
@@ -18,14 +26,14 @@ async fn main() -> AppResult<()> {
     let listener = TcpListener::bind("127.0.0.1:5555").await?;
     println!("Server listening on 127.0.0.1:5555");
 
-    // For demonstration: keep user profiles & messages in memory
-    // In a real system, store in a DB (e.g., Postgres)
-    let shared_state = clientinp::ServerState::new();
+    // In-memory by default; swap in `store::ObjectStoreBackend` to persist
+    // users and messages across restarts.
+    let shared_state = clientinp::ServerState::new(MemoryStore::new());
 
     loop {
         let (socket, _) = listener.accept().await?;
         let state_clone = shared_state.clone();
-        
+
         // Spawn a task to handle each client
         tokio::spawn(async move {
             if let Err(e) = handle_client(socket, state_clone).await {
@@ -35,40 +43,83 @@ async fn main() -> AppResult<()> {
     }
 }
 
-/// Handle a single client connection
-async fn handle_client(mut socket: TcpStream, state: clientinp::ServerState) -> AppResult<()> {
-    // For demonstration, we’ll read lines. You could do JSON lines, Protobuf, etc.
-    let mut buffer = vec![0u8; 1024];
-    let n = socket.read(&mut buffer).await?;
-    if n == 0 {
-        return Ok(());
-    }
+/// Handle a single client connection: first the `Handshake` state runs an
+/// Ed25519 + X25519 session handshake, then every frame in the
+/// `Authenticated` state is an AES-256-GCM-sealed `ServerCommand` over the
+/// same persistent, length-prefixed stream.
+async fn handle_client<S: Store>(socket: TcpStream, state: clientinp::ServerState<S>) -> AppResult<()> {
+    let mut framed = Framed::new(socket, LengthDelimitedCodec::new());
 
-    // Convert bytes to a command (this is naive; you’d parse JSON or another format)
-    let incoming = String::from_utf8_lossy(&buffer[..n]);
-    println!("Received from client: {:?}", incoming);
-
-    // Simple parse attempt: assume JSON command
-    let cmd: ServerCommand = match serde_json::from_str(&incoming) {
-        Ok(cmd) => cmd,
-        Err(_) => {
-            // If parse fails, just do nothing
-            socket.write_all(b"Malformed command").await?;
-            return Ok(());
-        }
+    // --- Handshake ---
+    let client_hello_frame = match framed.next().await {
+        Some(frame) => frame?,
+        None => return Ok(()),
     };
+    let client_hello: HandshakeHello = serde_json::from_slice(&client_hello_frame)
+        .map_err(|_| AppError::Internal("malformed handshake hello".to_string()))?;
+    let (server_hello, mut keys) = session::respond_to_client_hello(&state.identity, &client_hello)?;
+    framed
+        .send(Bytes::from(serde_json::to_vec(&server_hello).expect("serializable hello")))
+        .await?;
 
-    // Route to logic
-    let result = process_client(cmd, state).await;
+    // --- Authenticated: session is now encrypted; bind it to whichever
+    // username successfully signs in, so later commands don't need to
+    // resend credentials. ---
+    let mut bound_username: Option<String> = None;
 
-    // Send back response if relevant
-    let response = match result {
-        Ok(Some(response_str)) => response_str,
-        Ok(None) => "OK".to_string(),
-        Err(e) => format!("Error: {:?}", e),
-    };
+    while let Some(frame) = framed.next().await {
+        let frame = frame?;
+
+        let plaintext = match keys.open_frame(&frame) {
+            Ok(plaintext) => plaintext,
+            Err(e) => {
+                eprintln!("dropping frame that failed session decryption: {:?}", e);
+                continue;
+            }
+        };
+
+        let cmd: ServerCommand = match serde_json::from_slice(&plaintext) {
+            Ok(cmd) => cmd,
+            Err(_) => {
+                framed.send(Bytes::from(keys.seal_frame(b"Malformed command"))).await?;
+                continue;
+            }
+        };
+
+        // `SignUp`/`SignIn` are the only commands allowed before a session is
+        // bound to a username; every other command must match the bound
+        // username, and is rejected outright if nothing has signed in yet.
+        let is_auth_command = matches!(cmd, ServerCommand::SignUp { .. } | ServerCommand::SignIn { .. });
+        if !is_auth_command && bound_username.as_deref() != Some(command_principal(&cmd)) {
+            let response = format!("Error: {:?}", AppError::InvalidCredentials);
+            framed.send(Bytes::from(keys.seal_frame(response.as_bytes()))).await?;
+            continue;
+        }
+
+        let sign_in_username = match &cmd {
+            ServerCommand::SignIn { username, .. } => Some(username.clone()),
+            _ => None,
+        };
+        let is_sign_out = matches!(cmd, ServerCommand::SignOut { .. });
+
+        let result = process_client(cmd, state.clone()).await;
+
+        if result.is_ok() {
+            if let Some(username) = sign_in_username {
+                bound_username = Some(username);
+            } else if is_sign_out {
+                bound_username = None;
+            }
+        }
+
+        let response = match result {
+            Ok(Some(response_str)) => response_str,
+            Ok(None) => "OK".to_string(),
+            Err(e) => format!("Error: {:?}", e),
+        };
+
+        framed.send(Bytes::from(keys.seal_frame(response.as_bytes()))).await?;
+    }
 
-    socket.write_all(response.as_bytes()).await?;
     Ok(())
 }
-
@@ -0,0 +1,37 @@
+// src/password.rs
+//
+// Password hashing helpers, kept separate from `Padlock` since password
+// storage has nothing to do with message encryption/HMAC.
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+
+use crate::errors::{AppError, AppResult};
+
+// 19 MiB memory, 2 iterations, 1 lane: OWASP's baseline Argon2id parameters.
+fn argon2() -> Argon2<'static> {
+    let params = Params::new(19 * 1024, 2, 1, None).expect("valid argon2 params");
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+/// Hash a plaintext password into a PHC-encoded Argon2id string.
+pub fn hash_password(password: &str) -> AppResult<String> {
+    let salt = SaltString::generate(&mut rand_core::OsRng);
+    let hash = argon2()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| AppError::Internal(format!("password hashing failed: {e}")))?;
+    Ok(hash.to_string())
+}
+
+/// Verify a plaintext password against a stored PHC-encoded hash.
+///
+/// Returns `false` on any parse or mismatch error so callers can't
+/// distinguish a malformed hash from a wrong password.
+pub fn verify_password(stored_hash: &str, password: &str) -> bool {
+    let Ok(parsed) = PasswordHash::new(stored_hash) else {
+        return false;
+    };
+    argon2()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok()
+}
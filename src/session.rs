@@ -0,0 +1,155 @@
+// src/session.rs
+//
+// Authenticated, encrypted session handshake that runs once per TCP
+// connection before any `ServerCommand` is processed: each side presents
+// an Ed25519 identity, performs an X25519 ephemeral exchange, and derives
+// AES-256-GCM session keys via HKDF-SHA256. Every frame after the
+// handshake is encrypted and authenticated, with per-direction nonce
+// counters so replay within the session is detectable.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use hkdf::Hkdf;
+use rand_core::OsRng;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::errors::{AppError, AppResult};
+
+/// One side's handshake message: an Ed25519 identity public key, an
+/// ephemeral X25519 public key, and a signature over the ephemeral key
+/// proving the identity key controls it.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct HandshakeHello {
+    pub identity_pubkey: [u8; 32],
+    pub ephemeral_pubkey: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+fn sign_ephemeral(identity: &SigningKey, ephemeral_pubkey: &[u8; 32]) -> HandshakeHello {
+    let signature: Signature = identity.sign(ephemeral_pubkey);
+    HandshakeHello {
+        identity_pubkey: identity.verifying_key().to_bytes(),
+        ephemeral_pubkey: *ephemeral_pubkey,
+        signature: signature.to_bytes(),
+    }
+}
+
+fn verify_hello(hello: &HandshakeHello) -> AppResult<()> {
+    let verifying_key = VerifyingKey::from_bytes(&hello.identity_pubkey)
+        .map_err(|_| AppError::Internal("malformed identity public key".to_string()))?;
+    let signature = Signature::from_bytes(&hello.signature);
+    verifying_key
+        .verify(&hello.ephemeral_pubkey, &signature)
+        .map_err(|_| AppError::Internal("handshake signature did not verify".to_string()))
+}
+
+// Per-direction AES-256-GCM key, with a strictly increasing nonce counter
+// so a replayed frame reuses a nonce and is rejected by the recipient.
+struct DirectionalKey {
+    key: LessSafeKey,
+    counter: u64,
+}
+
+impl DirectionalKey {
+    fn new(key_bytes: [u8; 32]) -> Self {
+        let unbound = UnboundKey::new(&AES_256_GCM, &key_bytes).expect("32-byte key");
+        DirectionalKey { key: LessSafeKey::new(unbound), counter: 0 }
+    }
+
+    fn next_nonce(&mut self) -> Nonce {
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        nonce_bytes[..8].copy_from_slice(&self.counter.to_be_bytes());
+        self.counter += 1;
+        Nonce::assume_unique_for_key(nonce_bytes)
+    }
+}
+
+/// The session keys derived from the handshake: one AES-256-GCM key per
+/// direction, each with its own nonce counter.
+pub struct SessionKeys {
+    send: DirectionalKey,
+    recv: DirectionalKey,
+    expected_recv_counter: u64,
+}
+
+impl SessionKeys {
+    fn new(send_key: [u8; 32], recv_key: [u8; 32]) -> Self {
+        SessionKeys {
+            send: DirectionalKey::new(send_key),
+            recv: DirectionalKey::new(recv_key),
+            expected_recv_counter: 0,
+        }
+    }
+
+    /// Encrypt a frame for the peer, tagging it with the next send nonce.
+    pub fn seal_frame(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = self.send.next_nonce();
+        let mut in_out = plaintext.to_vec();
+        self.send
+            .key
+            .seal_in_place_append_tag(nonce, Aad::empty(), &mut in_out)
+            .expect("encryption failed");
+        in_out
+    }
+
+    /// Decrypt a frame from the peer. Nonces are derived from a counter
+    /// that must match the next expected value, so an out-of-order or
+    /// replayed frame is rejected rather than silently reused.
+    pub fn open_frame(&mut self, ciphertext: &[u8]) -> AppResult<Vec<u8>> {
+        let counter = self.expected_recv_counter;
+
+        let mut nonce_bytes = [0u8; NONCE_LEN];
+        nonce_bytes[..8].copy_from_slice(&counter.to_be_bytes());
+        let nonce = Nonce::assume_unique_for_key(nonce_bytes);
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .recv
+            .key
+            .open_in_place(nonce, Aad::empty(), &mut in_out)
+            .map_err(|_| AppError::Internal("session frame failed to decrypt or authenticate".to_string()))?;
+
+        // Only a frame that authenticated consumes its counter value, so a
+        // rejected frame doesn't desynchronize the rest of the session.
+        self.expected_recv_counter += 1;
+        self.recv.counter = counter + 1;
+        Ok(plaintext.to_vec())
+    }
+}
+
+/// Run the server side of the handshake against a client's hello,
+/// returning the server's response to send back and the derived session
+/// keys (client-to-server and server-to-client directions are distinct).
+pub fn respond_to_client_hello(
+    server_identity: &SigningKey,
+    client_hello: &HandshakeHello,
+) -> AppResult<(HandshakeHello, SessionKeys)> {
+    verify_hello(client_hello)?;
+
+    let server_ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+    let server_ephemeral_public = PublicKey::from(&server_ephemeral_secret);
+
+    let client_ephemeral_public = PublicKey::from(client_hello.ephemeral_pubkey);
+    let shared = server_ephemeral_secret.diffie_hellman(&client_ephemeral_public);
+
+    let (client_to_server, server_to_client) = derive_directional_keys(shared.as_bytes());
+
+    let server_hello = sign_ephemeral(server_identity, server_ephemeral_public.as_bytes());
+    // Server sends with `server_to_client`, receives client frames encrypted with `client_to_server`.
+    let keys = SessionKeys::new(server_to_client, client_to_server);
+
+    Ok((server_hello, keys))
+}
+
+fn derive_directional_keys(shared_secret: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let hk = Hkdf::<Sha256>::new(None, shared_secret);
+    let mut okm = [0u8; 64];
+    hk.expand(b"loveletter-session-v1", &mut okm)
+        .expect("64 is a valid HKDF-SHA256 output length");
+    let mut client_to_server = [0u8; 32];
+    let mut server_to_client = [0u8; 32];
+    client_to_server.copy_from_slice(&okm[..32]);
+    server_to_client.copy_from_slice(&okm[32..]);
+    (client_to_server, server_to_client)
+}
@@ -0,0 +1,63 @@
+// src/store/memory.rs
+//
+// The original in-process behavior, kept as the default `Store` impl:
+// everything lives in a couple of locked HashMaps and is lost on restart.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::errors::AppResult;
+use crate::inputs::UserMessage;
+
+use super::{Store, UserRecord};
+
+#[derive(Default)]
+pub struct MemoryStore {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    users: HashMap<String, UserRecord>,
+    // recipient username -> their messages
+    messages: HashMap<String, Vec<UserMessage>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn get_user(&self, username: &str) -> AppResult<Option<UserRecord>> {
+        Ok(self.inner.lock().unwrap().users.get(username).cloned())
+    }
+
+    async fn put_user(&self, username: &str, record: UserRecord) -> AppResult<()> {
+        self.inner.lock().unwrap().users.insert(username.to_string(), record);
+        Ok(())
+    }
+
+    async fn put_message(&self, msg: UserMessage) -> AppResult<()> {
+        self.inner.lock().unwrap().messages.entry(msg.to.clone()).or_default().push(msg);
+        Ok(())
+    }
+
+    async fn messages_for(&self, username: &str) -> AppResult<Vec<UserMessage>> {
+        Ok(self.inner.lock().unwrap().messages.get(username).cloned().unwrap_or_default())
+    }
+
+    async fn delete_message(&self, username: &str, msg_id: &str) -> AppResult<bool> {
+        let mut guard = self.inner.lock().unwrap();
+        let Some(messages) = guard.messages.get_mut(username) else {
+            return Ok(false);
+        };
+        let len_before = messages.len();
+        messages.retain(|m| m.id != msg_id);
+        Ok(messages.len() != len_before)
+    }
+}
@@ -0,0 +1,52 @@
+// src/store/mod.rs
+//
+// Persistence trait that `ServerState` is generic over, so swapping where
+// users and messages actually live doesn't touch `process_client` at all.
+// `memory` is the original HashMap/Vec behavior (lost on restart); `object`
+// is an S3-compatible backend (e.g. Garage) in the spirit of aerogramme's
+// encrypted-storage-over-Garage design: message bodies are already
+// ciphertext from `Padlock`, so the store just has to get opaque blobs in
+// and out, keyed by recipient.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::AppResult;
+use crate::inputs::UserMessage;
+
+mod memory;
+mod object;
+
+pub use memory::MemoryStore;
+pub use object::ObjectStoreBackend;
+
+/// A registered user's durable state: their password hash and the X25519
+/// public key their messages are sealed to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserRecord {
+    pub password_hash: String,
+    pub public_key: [u8; 32],
+}
+
+/// Durable storage for users and messages. In-process bookkeeping that
+/// isn't meant to survive a restart anyway stays out of here: topic
+/// subscriptions live on `ServerState`, and who is signed in lives in each
+/// connection's session.
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn get_user(&self, username: &str) -> AppResult<Option<UserRecord>>;
+    async fn put_user(&self, username: &str, record: UserRecord) -> AppResult<()>;
+
+    /// Store a message, keyed by its `to` recipient. Callers that want a
+    /// message visible to more than one user (e.g. topic fan-out) call
+    /// this once per recipient with `to` set accordingly.
+    async fn put_message(&self, msg: UserMessage) -> AppResult<()>;
+
+    /// All messages currently stored for `username`, in no particular
+    /// order.
+    async fn messages_for(&self, username: &str) -> AppResult<Vec<UserMessage>>;
+
+    /// Remove `username`'s copy of `msg_id`. Returns whether a message was
+    /// actually removed.
+    async fn delete_message(&self, username: &str, msg_id: &str) -> AppResult<bool>;
+}
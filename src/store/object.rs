@@ -0,0 +1,122 @@
+// src/store/object.rs
+//
+// `Store` backed by any S3-compatible bucket (Garage, MinIO, real S3),
+// following aerogramme's approach of layering encrypted mail storage over
+// Garage: message bodies are already ciphertext, so the bucket never sees
+// plaintext mail. User records (password hash, public key), message
+// metadata (sender, recipient, topics) and the per-recipient indexes are
+// stored as plain JSON.
+//
+// Layout:
+//   users/{username}.json          -- a `UserRecord`
+//   messages/{to}/{id}.json        -- a `UserMessage`, keyed by recipient+id
+//   messages/{to}/index.json       -- a `Vec<String>` of that recipient's message ids
+//
+// The index is a read-modify-write, not a compare-and-swap: two writers
+// touching the same recipient concurrently can race and drop one of their
+// updates. Fine for a single-writer-per-recipient workload; a busy shared
+// inbox would need the bucket's conditional-put support to close that gap.
+
+use async_trait::async_trait;
+use object_store::path::Path;
+use object_store::{Error as ObjectStoreError, ObjectStore as DynObjectStore};
+
+use crate::errors::{AppError, AppResult};
+use crate::inputs::UserMessage;
+
+use super::{Store, UserRecord};
+
+pub struct ObjectStoreBackend {
+    store: Box<dyn DynObjectStore>,
+}
+
+impl ObjectStoreBackend {
+    pub fn new(store: Box<dyn DynObjectStore>) -> Self {
+        Self { store }
+    }
+
+    fn user_path(username: &str) -> Path {
+        Path::from(format!("users/{username}.json"))
+    }
+
+    fn message_path(to: &str, id: &str) -> Path {
+        Path::from(format!("messages/{to}/{id}.json"))
+    }
+
+    fn index_path(to: &str) -> Path {
+        Path::from(format!("messages/{to}/index.json"))
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, path: &Path) -> AppResult<Option<T>> {
+        match self.store.get(path).await {
+            Ok(result) => {
+                let bytes = result.bytes().await.map_err(store_err)?;
+                let value = serde_json::from_slice(&bytes)
+                    .map_err(|e| AppError::Internal(format!("corrupt object at {path}: {e}")))?;
+                Ok(Some(value))
+            }
+            Err(ObjectStoreError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(store_err(e)),
+        }
+    }
+
+    async fn put_json<T: serde::Serialize + Sync>(&self, path: &Path, value: &T) -> AppResult<()> {
+        let bytes = serde_json::to_vec(value)
+            .map_err(|e| AppError::Internal(format!("failed to serialize object for {path}: {e}")))?;
+        self.store.put(path, bytes.into()).await.map_err(store_err)?;
+        Ok(())
+    }
+
+    async fn index_for(&self, to: &str) -> AppResult<Vec<String>> {
+        Ok(self.get_json(&Self::index_path(to)).await?.unwrap_or_default())
+    }
+}
+
+fn store_err(e: ObjectStoreError) -> AppError {
+    AppError::Internal(format!("object store error: {e}"))
+}
+
+#[async_trait]
+impl Store for ObjectStoreBackend {
+    async fn get_user(&self, username: &str) -> AppResult<Option<UserRecord>> {
+        self.get_json(&Self::user_path(username)).await
+    }
+
+    async fn put_user(&self, username: &str, record: UserRecord) -> AppResult<()> {
+        self.put_json(&Self::user_path(username), &record).await
+    }
+
+    async fn put_message(&self, msg: UserMessage) -> AppResult<()> {
+        let mut index = self.index_for(&msg.to).await?;
+        if !index.contains(&msg.id) {
+            index.push(msg.id.clone());
+            self.put_json(&Self::index_path(&msg.to), &index).await?;
+        }
+        self.put_json(&Self::message_path(&msg.to, &msg.id), &msg).await
+    }
+
+    async fn messages_for(&self, username: &str) -> AppResult<Vec<UserMessage>> {
+        let mut messages = Vec::new();
+        for id in self.index_for(username).await? {
+            if let Some(msg) = self.get_json(&Self::message_path(username, &id)).await? {
+                messages.push(msg);
+            }
+        }
+        Ok(messages)
+    }
+
+    async fn delete_message(&self, username: &str, msg_id: &str) -> AppResult<bool> {
+        let mut index = self.index_for(username).await?;
+        let len_before = index.len();
+        index.retain(|id| id != msg_id);
+        if index.len() == len_before {
+            return Ok(false);
+        }
+        self.put_json(&Self::index_path(username), &index).await?;
+        self.store
+            .delete(&Self::message_path(username, msg_id))
+            .await
+            .map_err(store_err)?;
+        Ok(true)
+    }
+}